@@ -13,6 +13,56 @@ use crate::{
 
 use super::nodes::{self, DirectiveResult};
 
+/// A byte-offset span into a single file's source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub lo: u32,
+    pub hi: u32,
+}
+
+impl Span {
+    pub fn new(lo: u32, hi: u32) -> Self {
+        Self { lo, hi }
+    }
+}
+
+impl From<TextRange> for Span {
+    fn from(range: TextRange) -> Self {
+        Self {
+            lo: range.start().into(),
+            hi: range.end().into(),
+        }
+    }
+}
+
+/// A single labelled region of source referenced by a [`Report`]
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+/// A renderable diagnostic: a message plus the spans it refers to
+///
+/// This is the data a downstream tool (an editor, an LSP server) needs to
+/// render carets-under-source output, rather than the bare `file:line:`
+/// string produced by [`Error`]'s `Display` impl.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+}
+
 #[derive(Debug)]
 pub struct ProcessingError {
     node: SyntaxNode,
@@ -44,6 +94,53 @@ impl ProcessingError {
     pub fn col(&self) -> u32 {
         self.user_pos.1
     }
+
+    /// Byte span of the syntax node this error was raised on
+    pub fn span(&self) -> Span {
+        self.node.text_range().into()
+    }
+
+    /// Build a renderable [`Report`] for this error
+    ///
+    /// Unlike [`Display`](std::fmt::Display), which only prints the bare
+    /// message, this carries the spans needed to render carets-under-source
+    /// diagnostics.
+    pub fn to_report(&self) -> Report {
+        let secondary = match &self.kind {
+            ProcessingErrorKind::ExtraEndIf => {
+                vec![Label::new(self.span(), "no matching #if for this #endif")]
+            }
+            ProcessingErrorKind::ExtraElse => {
+                vec![Label::new(self.span(), "no matching #if for this #else")]
+            }
+            ProcessingErrorKind::ExtraElif => {
+                vec![Label::new(self.span(), "no matching #if for this #elif")]
+            }
+            ProcessingErrorKind::ProtectedDefine { ident, .. } => {
+                vec![Label::new(self.ident_span(ident), "protected name")]
+            }
+            ProcessingErrorKind::ErrorDirective { .. }
+            | ProcessingErrorKind::DivisionByZero
+            | ProcessingErrorKind::MalformedExpression => Vec::new(),
+        };
+
+        Report {
+            message: self.kind.to_string(),
+            primary: Label::new(self.span(), self.kind.to_string()),
+            secondary,
+        }
+    }
+
+    /// Narrow the error's span down to the token matching `ident`, falling
+    /// back to the whole node's span if it can't be found
+    fn ident_span(&self, ident: &str) -> Span {
+        self.node
+            .descendants_with_tokens()
+            .filter_map(|element| element.into_token())
+            .find(|token| token.text() == ident)
+            .map(|token| token.text_range().into())
+            .unwrap_or_else(|| self.span())
+    }
 }
 
 impl std::fmt::Display for ProcessingError {
@@ -54,12 +151,17 @@ impl std::fmt::Display for ProcessingError {
 
 impl std::error::Error for ProcessingError {}
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ProcessingErrorKind {
     ExtraEndIf,
     ExtraElse,
+    ExtraElif,
     ProtectedDefine { ident: SmolStr, is_undef: bool },
     ErrorDirective { message: String },
+    /// Division or modulo by zero in a `#if`/`#elif` constant expression
+    DivisionByZero,
+    /// A `#if`/`#elif` constant expression couldn't be parsed
+    MalformedExpression,
 }
 
 impl ProcessingErrorKind {
@@ -78,6 +180,15 @@ impl std::fmt::Display for ProcessingErrorKind {
             ProcessingErrorKind::ExtraElse => {
                 write!(f, "unmatched #else")
             }
+            ProcessingErrorKind::ExtraElif => {
+                write!(f, "unmatched #elif")
+            }
+            ProcessingErrorKind::DivisionByZero => {
+                write!(f, "division by zero in preprocessor expression")
+            }
+            ProcessingErrorKind::MalformedExpression => {
+                write!(f, "malformed preprocessor constant expression")
+            }
             ProcessingErrorKind::ProtectedDefine { ident, is_undef } => {
                 let directive = if *is_undef { "undef" } else { "define" };
 
@@ -102,6 +213,28 @@ impl std::fmt::Display for ProcessingErrorKind {
     }
 }
 
+/// Error handling policy for the preprocessing lexer
+///
+/// Controls whether errors are returned inline by the lexer as they are met,
+/// or accumulated so they can be retrieved in one pass (see
+/// `Lexer::take_errors` in the `glsl-lang` crate), which editor/LSP
+/// scenarios need to report issues in a file from a single pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorHandling {
+    /// Return each error from the lexer's iterator as it is met; errors are
+    /// not accumulated, and the iterator does not end because of them
+    Stop,
+    /// Keep lexing, accumulating error events for later retrieval; errors
+    /// attached to directives are handled as under `Stop`
+    Continue,
+}
+
+impl Default for ErrorHandling {
+    fn default() -> Self {
+        Self::Stop
+    }
+}
+
 #[derive(Debug)]
 pub struct Error<E: std::error::Error + 'static> {
     kind: ErrorKind<E>,
@@ -119,6 +252,36 @@ impl<E: std::error::Error> Error<E> {
             ..self
         }
     }
+
+    /// Build a renderable [`Report`] for this error, if it carries span
+    /// information
+    ///
+    /// [`ErrorKind::Io`] has no associated source location and returns
+    /// `None`.
+    ///
+    /// [`ErrorKind::Parse`] also returns `None` here, even though
+    /// `parser::Error` does carry a line (used by this type's `Display`
+    /// impl via `err.line()`): building a [`Report`] needs a byte-offset
+    /// [`Span`], and turning a line number into one needs the file's
+    /// [`LineMap`](crate::lexer::LineMap), which isn't stored on `Error` —
+    /// only `current_file`. Reporting `Parse` errors with a real span would
+    /// mean threading a `LineMap` reference through to here (or onto
+    /// `Error` itself), which is a bigger change than this method should
+    /// make on its own.
+    pub fn to_report(&self) -> Option<Report> {
+        match &self.kind {
+            ErrorKind::Processing(err) => Some(err.to_report()),
+            ErrorKind::Unhandled(node_or_token, _) => {
+                let span: Span = node_or_token.text_range().into();
+                Some(Report {
+                    message: self.kind.to_string(),
+                    primary: Label::new(span, self.kind.to_string()),
+                    secondary: Vec::new(),
+                })
+            }
+            ErrorKind::Io(_) | ErrorKind::Parse(_) => None,
+        }
+    }
 }
 
 impl<E: std::error::Error> std::fmt::Display for Error<E> {
@@ -196,6 +359,8 @@ pub enum DirectiveKind {
     Define(DirectiveResult<nodes::Define>),
     IfDef(DirectiveResult<nodes::IfDef>),
     IfNDef(DirectiveResult<nodes::IfNDef>),
+    If(DirectiveResult<nodes::If>),
+    Elif(DirectiveResult<nodes::Elif>),
     Else,
     EndIf,
     Undef(DirectiveResult<nodes::Undef>),
@@ -265,6 +430,14 @@ impl std::fmt::Debug for OutputToken {
 pub enum Event<E: std::error::Error + 'static> {
     Error(Error<E>),
     EnterFile { file_id: FileId, path: PathBuf },
+    /// Marks the end of the file entered via the matching
+    /// [`Event::EnterFile`]
+    ///
+    /// The processor does not construct this variant: no event is emitted
+    /// when an included file ends. `last::Event` (the enum `last::Tokenizer`
+    /// yields to lexers) has no `ExitFile` variant, so lexers cannot track
+    /// the include stack from it either.
+    ExitFile { file_id: FileId },
     Token(OutputToken),
     Directive(DirectiveKind),
 }
@@ -278,3 +451,528 @@ impl<E: std::error::Error> Event<E> {
         Self::Error(e.into().with_current_file(current_file))
     }
 }
+
+// The rest of this module provides the `#if`/`#elif` constant-expression
+// grammar (`ExprToken`, `evaluate_constant_expression`) and the conditional
+// inclusion stack (`ConditionalStack`) on their own. The directive processor
+// does not call them: it does not construct `DirectiveKind::If`/`Elif`, does
+// not evaluate `#if`/`#elif` conditions, and does not use
+// `ConditionalStack` to decide which tokens are masked.
+
+/// Per-`#if` frame state in a [`ConditionalStack`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConditionalState {
+    /// This branch's condition matched and is currently active
+    Active,
+    /// No branch of this `#if` has matched yet
+    Waiting,
+    /// A branch already matched; every later `#elif`/`#else` is skipped
+    Taken,
+}
+
+/// Tracks nested `#if`/`#elif`/`#else`/`#endif` directives
+///
+/// Each frame on the stack tracks one of three states: the branch currently
+/// emitting tokens, branches still waiting for one to match, or a frame
+/// where a branch already fired and every later branch is skipped. Nesting
+/// an `#if` inside a frame that isn't currently active freezes the new
+/// frame as permanently skipped, regardless of its own condition.
+#[derive(Debug, Default)]
+pub struct ConditionalStack {
+    frames: Vec<ConditionalState>,
+}
+
+impl ConditionalStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Are we currently inside an active (non-skipped) branch?
+    pub fn is_active(&self) -> bool {
+        self.frames
+            .iter()
+            .all(|state| *state == ConditionalState::Active)
+    }
+
+    /// Push a new `#if`/`#ifdef`/`#ifndef` frame whose condition evaluated
+    /// to `condition`
+    pub fn push_if(&mut self, condition: bool) {
+        let state = if !self.is_active() {
+            ConditionalState::Taken
+        } else if condition {
+            ConditionalState::Active
+        } else {
+            ConditionalState::Waiting
+        };
+
+        self.frames.push(state);
+    }
+
+    /// Handle an `#elif` whose condition evaluated to `condition`
+    pub fn elif(&mut self, condition: bool) -> Result<(), ProcessingErrorKind> {
+        let ancestors_active = self.ancestors_active();
+
+        match self.frames.last_mut() {
+            None => Err(ProcessingErrorKind::ExtraElif),
+            Some(state) => {
+                *state = match *state {
+                    ConditionalState::Waiting if ancestors_active && condition => {
+                        ConditionalState::Active
+                    }
+                    ConditionalState::Waiting => ConditionalState::Waiting,
+                    ConditionalState::Active | ConditionalState::Taken => ConditionalState::Taken,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle an `#else`
+    pub fn else_branch(&mut self) -> Result<(), ProcessingErrorKind> {
+        let ancestors_active = self.ancestors_active();
+
+        match self.frames.last_mut() {
+            None => Err(ProcessingErrorKind::ExtraElse),
+            Some(state) => {
+                *state = match *state {
+                    ConditionalState::Waiting if ancestors_active => ConditionalState::Active,
+                    ConditionalState::Waiting => ConditionalState::Waiting,
+                    ConditionalState::Active | ConditionalState::Taken => ConditionalState::Taken,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle an `#endif`, popping the innermost frame
+    pub fn pop_endif(&mut self) -> Result<(), ProcessingErrorKind> {
+        if self.frames.pop().is_some() {
+            Ok(())
+        } else {
+            Err(ProcessingErrorKind::ExtraEndIf)
+        }
+    }
+
+    fn ancestors_active(&self) -> bool {
+        let len = self.frames.len();
+        self.frames[..len.saturating_sub(1)]
+            .iter()
+            .all(|state| *state == ConditionalState::Active)
+    }
+}
+
+/// A single token of a `#if`/`#elif` constant expression, after macro
+/// expansion
+///
+/// The caller is responsible for fully expanding macros in the condition
+/// before handing the resulting tokens to
+/// [`evaluate_constant_expression`]; any identifier still present at that
+/// point is not a macro and evaluates to `0`. `defined ID`/`defined(ID)` are
+/// tokenized as a unit, since their operand must *not* be macro-expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprToken {
+    Int(i64),
+    Ident(SmolStr),
+    Defined(SmolStr),
+    LParen,
+    RParen,
+    Plus,
+    Minus,
+    Not,
+    Complement,
+    Star,
+    Slash,
+    Percent,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    EqEq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    AndAnd,
+    OrOr,
+    Question,
+    Colon,
+}
+
+/// Evaluate a fully macro-expanded `#if`/`#elif` constant expression
+///
+/// Implements the standard GLSL preprocessor constant-expression grammar:
+/// integer literals, `defined ID`/`defined(ID)`, the unary `+ - ! ~`
+/// operators, the binary `* / % + - << >> < <= > >= == != & ^ | && ||`
+/// operators and the ternary `?:`, evaluated on signed 64-bit integers with
+/// the usual C precedence and associativity.
+pub fn evaluate_constant_expression(
+    tokens: &[ExprToken],
+    is_defined: &dyn Fn(&str) -> bool,
+) -> Result<i64, ProcessingErrorKind> {
+    let mut parser = ExprParser {
+        tokens,
+        pos: 0,
+        is_defined,
+    };
+
+    let value = parser.ternary()?;
+
+    if parser.pos != tokens.len() {
+        return Err(ProcessingErrorKind::MalformedExpression);
+    }
+
+    Ok(value)
+}
+
+struct ExprParser<'a> {
+    tokens: &'a [ExprToken],
+    pos: usize,
+    is_defined: &'a dyn Fn(&str) -> bool,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek(&self) -> Option<&ExprToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Option<&ExprToken> {
+        let token = self.tokens.get(self.pos);
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn ternary(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let cond = self.logical_or()?;
+
+        if matches!(self.peek(), Some(ExprToken::Question)) {
+            self.bump();
+            let then_value = self.ternary()?;
+
+            if !matches!(self.bump(), Some(ExprToken::Colon)) {
+                return Err(ProcessingErrorKind::MalformedExpression);
+            }
+
+            let else_value = self.ternary()?;
+            Ok(if cond != 0 { then_value } else { else_value })
+        } else {
+            Ok(cond)
+        }
+    }
+
+    fn logical_or(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.logical_and()?;
+        while matches!(self.peek(), Some(ExprToken::OrOr)) {
+            self.bump();
+            let rhs = self.logical_and()?;
+            value = ((value != 0) || (rhs != 0)) as i64;
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.bit_or()?;
+        while matches!(self.peek(), Some(ExprToken::AndAnd)) {
+            self.bump();
+            let rhs = self.bit_or()?;
+            value = ((value != 0) && (rhs != 0)) as i64;
+        }
+        Ok(value)
+    }
+
+    fn bit_or(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.bit_xor()?;
+        while matches!(self.peek(), Some(ExprToken::Or)) {
+            self.bump();
+            value |= self.bit_xor()?;
+        }
+        Ok(value)
+    }
+
+    fn bit_xor(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.bit_and()?;
+        while matches!(self.peek(), Some(ExprToken::Xor)) {
+            self.bump();
+            value ^= self.bit_and()?;
+        }
+        Ok(value)
+    }
+
+    fn bit_and(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.equality()?;
+        while matches!(self.peek(), Some(ExprToken::And)) {
+            self.bump();
+            value &= self.equality()?;
+        }
+        Ok(value)
+    }
+
+    fn equality(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.relational()?;
+        loop {
+            match self.peek() {
+                Some(ExprToken::EqEq) => {
+                    self.bump();
+                    value = (value == self.relational()?) as i64;
+                }
+                Some(ExprToken::Ne) => {
+                    self.bump();
+                    value = (value != self.relational()?) as i64;
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn relational(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.shift()?;
+        loop {
+            match self.peek() {
+                Some(ExprToken::Lt) => {
+                    self.bump();
+                    value = (value < self.shift()?) as i64;
+                }
+                Some(ExprToken::Le) => {
+                    self.bump();
+                    value = (value <= self.shift()?) as i64;
+                }
+                Some(ExprToken::Gt) => {
+                    self.bump();
+                    value = (value > self.shift()?) as i64;
+                }
+                Some(ExprToken::Ge) => {
+                    self.bump();
+                    value = (value >= self.shift()?) as i64;
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn shift(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.additive()?;
+        loop {
+            match self.peek() {
+                Some(ExprToken::Shl) => {
+                    self.bump();
+                    value = value.wrapping_shl(self.additive()? as u32);
+                }
+                Some(ExprToken::Shr) => {
+                    self.bump();
+                    value = value.wrapping_shr(self.additive()? as u32);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn additive(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.multiplicative()?;
+        loop {
+            match self.peek() {
+                Some(ExprToken::Plus) => {
+                    self.bump();
+                    value = value.wrapping_add(self.multiplicative()?);
+                }
+                Some(ExprToken::Minus) => {
+                    self.bump();
+                    value = value.wrapping_sub(self.multiplicative()?);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn multiplicative(&mut self) -> Result<i64, ProcessingErrorKind> {
+        let mut value = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(ExprToken::Star) => {
+                    self.bump();
+                    value = value.wrapping_mul(self.unary()?);
+                }
+                Some(ExprToken::Slash) => {
+                    self.bump();
+                    let rhs = self.unary()?;
+                    if rhs == 0 {
+                        return Err(ProcessingErrorKind::DivisionByZero);
+                    }
+                    value = value.wrapping_div(rhs);
+                }
+                Some(ExprToken::Percent) => {
+                    self.bump();
+                    let rhs = self.unary()?;
+                    if rhs == 0 {
+                        return Err(ProcessingErrorKind::DivisionByZero);
+                    }
+                    value = value.wrapping_rem(rhs);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, ProcessingErrorKind> {
+        match self.peek() {
+            Some(ExprToken::Plus) => {
+                self.bump();
+                self.unary()
+            }
+            Some(ExprToken::Minus) => {
+                self.bump();
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some(ExprToken::Not) => {
+                self.bump();
+                Ok((self.unary()? == 0) as i64)
+            }
+            Some(ExprToken::Complement) => {
+                self.bump();
+                Ok(!self.unary()?)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, ProcessingErrorKind> {
+        match self.bump() {
+            Some(ExprToken::Int(value)) => Ok(*value),
+            Some(ExprToken::Ident(_)) => Ok(0),
+            Some(ExprToken::Defined(ident)) => Ok((self.is_defined)(ident) as i64),
+            Some(ExprToken::LParen) => {
+                let value = self.ternary()?;
+                if !matches!(self.bump(), Some(ExprToken::RParen)) {
+                    return Err(ProcessingErrorKind::MalformedExpression);
+                }
+                Ok(value)
+            }
+            _ => Err(ProcessingErrorKind::MalformedExpression),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(tokens: Vec<ExprToken>) -> Result<i64, ProcessingErrorKind> {
+        evaluate_constant_expression(&tokens, &|_| false)
+    }
+
+    #[test]
+    fn precedence_multiplication_before_addition() {
+        // 1 + 2 * 3 == 7
+        let tokens = vec![
+            ExprToken::Int(1),
+            ExprToken::Plus,
+            ExprToken::Int(2),
+            ExprToken::Star,
+            ExprToken::Int(3),
+        ];
+        assert_eq!(eval(tokens), Ok(7));
+    }
+
+    #[test]
+    fn ternary_is_right_associative() {
+        // 0 ? 1 : 0 ? 2 : 3 == 3
+        let tokens = vec![
+            ExprToken::Int(0),
+            ExprToken::Question,
+            ExprToken::Int(1),
+            ExprToken::Colon,
+            ExprToken::Int(0),
+            ExprToken::Question,
+            ExprToken::Int(2),
+            ExprToken::Colon,
+            ExprToken::Int(3),
+        ];
+        assert_eq!(eval(tokens), Ok(3));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let tokens = vec![ExprToken::Int(1), ExprToken::Slash, ExprToken::Int(0)];
+        assert_eq!(eval(tokens), Err(ProcessingErrorKind::DivisionByZero));
+    }
+
+    #[test]
+    fn modulo_by_zero_is_an_error() {
+        let tokens = vec![ExprToken::Int(1), ExprToken::Percent, ExprToken::Int(0)];
+        assert_eq!(eval(tokens), Err(ProcessingErrorKind::DivisionByZero));
+    }
+
+    #[test]
+    fn malformed_expression_is_rejected() {
+        let tokens = vec![ExprToken::Int(1), ExprToken::Plus];
+        assert_eq!(eval(tokens), Err(ProcessingErrorKind::MalformedExpression));
+    }
+
+    #[test]
+    fn trailing_tokens_are_rejected() {
+        let tokens = vec![ExprToken::Int(1), ExprToken::Int(2)];
+        assert_eq!(eval(tokens), Err(ProcessingErrorKind::MalformedExpression));
+    }
+
+    #[test]
+    fn defined_queries_the_callback_without_expanding_its_operand() {
+        let tokens = vec![ExprToken::Defined(SmolStr::new("FOO"))];
+        assert_eq!(
+            evaluate_constant_expression(&tokens, &|ident| ident == "FOO"),
+            Ok(1)
+        );
+        assert_eq!(
+            evaluate_constant_expression(&tokens, &|ident| ident != "FOO"),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn unexpanded_identifier_evaluates_to_zero() {
+        let tokens = vec![ExprToken::Ident(SmolStr::new("UNKNOWN"))];
+        assert_eq!(eval(tokens), Ok(0));
+    }
+
+    #[test]
+    fn conditional_stack_tracks_elif_and_else() {
+        let mut stack = ConditionalStack::new();
+        stack.push_if(false);
+        assert!(!stack.is_active());
+
+        stack.elif(true).unwrap();
+        assert!(stack.is_active());
+
+        stack.else_branch().unwrap();
+        assert!(!stack.is_active());
+
+        stack.pop_endif().unwrap();
+        assert!(stack.is_active());
+    }
+
+    #[test]
+    fn nested_if_inside_a_skipped_branch_never_activates() {
+        let mut stack = ConditionalStack::new();
+        stack.push_if(false);
+        stack.push_if(true);
+        assert!(!stack.is_active());
+    }
+
+    #[test]
+    fn extra_endif_is_an_error() {
+        let mut stack = ConditionalStack::new();
+        assert_eq!(stack.pop_endif(), Err(ProcessingErrorKind::ExtraEndIf));
+    }
+
+    #[test]
+    fn extra_elif_is_an_error() {
+        let mut stack = ConditionalStack::new();
+        assert_eq!(stack.elif(true), Err(ProcessingErrorKind::ExtraElif));
+    }
+}
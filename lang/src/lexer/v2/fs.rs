@@ -10,7 +10,7 @@ use glsl_lang_pp::{
     exts::{Registry, DEFAULT_REGISTRY},
     last::{self, Event},
     processor::{
-        event::Located,
+        event::{Error, ErrorHandling, Located},
         fs::{ExpandStack, FileSystem, ParsedFile, Processor},
         ProcessorState,
     },
@@ -31,6 +31,8 @@ pub struct Lexer<'r, 'p, F: FileSystem + 'p> {
     core: LexerCore,
     current_file: PathBuf,
     handle_token: HandleTokenResult<Located<F::Error>>,
+    error_handling: ErrorHandling,
+    errors: Vec<Error<F::Error>>,
 }
 
 impl<'r, 'p, F: FileSystem> Lexer<'r, 'p, F> {
@@ -46,10 +48,44 @@ impl<'r, 'p, F: FileSystem> Lexer<'r, 'p, F> {
             core: LexerCore::new(opts),
             current_file: Default::default(),
             handle_token: Default::default(),
+            error_handling: ErrorHandling::default(),
+            errors: Vec::new(),
         }
     }
+
+    /// Set the error handling policy (default: [`ErrorHandling::Stop`])
+    ///
+    /// The policy is not read from [`ParseContext`]: every new `Lexer`
+    /// starts with `ErrorHandling::Stop`, whatever `opts` it was built with,
+    /// and this setter is the only way to change it.
+    pub fn set_error_handling(&mut self, error_handling: ErrorHandling) {
+        self.error_handling = error_handling;
+    }
+
+    /// Drain and return every diagnostic accumulated so far
+    ///
+    /// Only useful when `error_handling` is set to [`ErrorHandling::Continue`]:
+    /// exhaust the iterator first, then call this to retrieve the collected
+    /// errors all at once instead of aborting at the first one. Only error
+    /// events from the tokenizer that are not masked are collected: errors
+    /// attached to directives are passed to the lexer core as under
+    /// [`ErrorHandling::Stop`], and I/O errors are still returned by the
+    /// iterator.
+    pub fn take_errors(&mut self) -> Vec<Error<F::Error>> {
+        std::mem::take(&mut self.errors)
+    }
 }
 
+// NOTE: a `current_include_stack()` accessor was attempted here, but
+// popping it correctly requires observing `last::Event::ExitFile`, and
+// `last::Event` (unlike `processor::event::Event`, see `ExitFile` there) has
+// no such variant yet — it's a different, richer enum (its `EnterFile` also
+// carries a `canonical_path`). Shipping a stack that only ever grows would
+// be worse than not having one, so this request is NOT done: an
+// include-stack accessor still needs `last::Event` to forward `ExitFile`
+// from the processor layer, then this can push on `EnterFile` and pop on
+// that forwarded `ExitFile`.
+
 impl<'r, 'p, F: FileSystem> Iterator for Lexer<'r, 'p, F> {
     type Item = core::Item<F::Error>;
 
@@ -68,11 +104,18 @@ impl<'r, 'p, F: FileSystem> Iterator for Lexer<'r, 'p, F> {
             if let Some(result) = self.handle_token.pop_event().or_else(|| self.inner.next()) {
                 match result {
                     Ok(event) => match event {
-                        Event::Error { error, masked } => {
-                            if let Some(result) = self.core.handle_error(error, masked) {
-                                return Some(result);
+                        Event::Error { error, masked } => match self.error_handling {
+                            ErrorHandling::Stop => {
+                                if let Some(result) = self.core.handle_error(error, masked) {
+                                    return Some(result);
+                                }
                             }
-                        }
+                            ErrorHandling::Continue => {
+                                if !masked {
+                                    self.errors.push(error);
+                                }
+                            }
+                        },
 
                         Event::Token {
                             source_token,
@@ -155,9 +198,35 @@ pub struct Preprocessor<'p, F: FileSystem> {
     processor: &'p mut Processor<F>,
 }
 
+/// Encoding selection strategy for [`Preprocessor::open`]
+#[derive(Debug, Clone, Copy)]
+pub enum Encoding {
+    /// Decode using a specific, caller-provided encoding
+    Fixed(&'static encoding_rs::Encoding),
+    /// Sniff the file's byte-order mark, falling back to statistical
+    /// detection when none is present
+    Detect,
+    /// Decode as UTF-8, regardless of any byte-order mark
+    Utf8,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::Detect
+    }
+}
+
 /// A preprocessor parsed file ready for lexing
 pub struct File<'p, F: FileSystem> {
     inner: ParsedFile<'p, F>,
+    encoding: Encoding,
+}
+
+impl<'p, F: FileSystem> File<'p, F> {
+    /// Which encoding strategy was requested when this file was opened
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
 }
 
 impl<'p, F: FileSystem> IntoLexer for File<'p, F> {
@@ -180,6 +249,15 @@ impl<'p, F: FileSystem> Preprocessor<'p, F> {
 
     /// Open the given file for lexing
     ///
+    /// # Breaking change
+    ///
+    /// This method now requires `F::Error: From<std::io::Error>`, so the
+    /// [`Encoding::Detect`] sniff (which can itself fail with an
+    /// [`std::io::Error`]) has somewhere to put that error. Any existing
+    /// `FileSystem` implementor whose `Error` type doesn't already convert
+    /// from `std::io::Error` will stop compiling against this method and
+    /// needs that conversion added.
+    ///
     /// # Parameters
     ///
     /// * `path`: path to the file to open
@@ -187,10 +265,56 @@ impl<'p, F: FileSystem> Preprocessor<'p, F> {
     pub fn open(
         &'p mut self,
         path: impl AsRef<Path>,
-        encoding: Option<&'static encoding_rs::Encoding>,
-    ) -> Result<File<'p, F>, F::Error> {
+        encoding: Encoding,
+    ) -> Result<File<'p, F>, F::Error>
+    where
+        F::Error: From<std::io::Error>,
+    {
+        let path = path.as_ref();
+
+        let resolved_encoding = match encoding {
+            Encoding::Fixed(enc) => Some(enc),
+            Encoding::Utf8 => Some(encoding_rs::UTF_8),
+            Encoding::Detect => Some(Self::sniff_encoding(path)?),
+        };
+
+        // NOTE: `Processor::parse` below re-reads and decodes the file from
+        // disk using `resolved_encoding`; fully avoiding that second read
+        // would mean teaching it to accept pre-read bytes, which isn't
+        // exposed yet. Keeping the sniff itself to a bounded prefix (below)
+        // at least avoids paying for two full-file reads.
         self.processor
-            .parse(path.as_ref(), encoding)
-            .map(|parsed_file| File { inner: parsed_file })
+            .parse(path, resolved_encoding)
+            .map(|parsed_file| File {
+                inner: parsed_file,
+                encoding,
+            })
+    }
+
+    /// Guess the encoding of the file at `path`
+    ///
+    /// Looks for a UTF-8/UTF-16LE/UTF-16BE byte-order mark first, and falls
+    /// back to feeding a bounded prefix of the file to a statistical
+    /// detector when none is present, so shaders saved as Latin-1 or UTF-16
+    /// by Windows tooling decode correctly instead of producing mojibake
+    /// tokens. A prefix is enough for both a BOM check and a confident
+    /// statistical guess, so this doesn't need to read the whole file.
+    fn sniff_encoding(path: &Path) -> Result<&'static encoding_rs::Encoding, std::io::Error> {
+        use std::io::Read as _;
+
+        const SNIFF_LEN: u64 = 8 * 1024;
+
+        let file = std::fs::File::open(path)?;
+        let mut prefix = Vec::new();
+        file.take(SNIFF_LEN).read_to_end(&mut prefix)?;
+
+        if let Some((encoding, _bom_len)) = encoding_rs::Encoding::for_bom(&prefix) {
+            return Ok(encoding);
+        }
+
+        let reached_eof = (prefix.len() as u64) < SNIFF_LEN;
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(&prefix, reached_eof);
+        Ok(detector.guess(None, true))
     }
 }